@@ -1,12 +1,13 @@
 use std::io::Write;
-use std::str::FromStr;
 
 use clap::{App, Arg, ArgMatches};
 use indexmap::IndexSet;
 
 use crate::{
     error::Error,
+    patchid::{self, UpstreamPatchIds},
     patchname::PatchName,
+    patchspec::{parse_spec, Destination},
     stack::{ConflictMode, Stack, StackTransaction},
 };
 
@@ -25,14 +26,21 @@ fn get_app() -> App<'static> {
         .arg(
             Arg::new("patch")
                 .help("Patch to go to")
+                .long_help(
+                    "Patch to go to. In addition to a patch name, this may be \
+                     `{base}` for the stack base, a signed offset like `+2` or \
+                     `-1` relative to the current top, or a patch name with a \
+                     `~N` (or `^` for `~1`) suffix meaning N patches earlier in \
+                     stack order.",
+                )
                 .required(true)
-                .validator(PatchName::from_str)
-                .forbid_empty_values(true),
+                .forbid_empty_values(true)
+                .allow_hyphen_values(true),
         )
 }
 
 fn run(matches: &ArgMatches) -> super::Result {
-    let patchname: PatchName = matches.value_of_t("patch").unwrap();
+    let patch_spec = matches.value_of("patch").unwrap();
     let repo = git2::Repository::open_from_env()?;
     let stack = Stack::from_branch(&repo, None)?;
 
@@ -47,7 +55,163 @@ fn run(matches: &ArgMatches) -> super::Result {
         stack.check_worktree_clean()?;
     }
 
-    let patchname = if stack.state.patches.contains_key(&patchname) {
+    let destination = match parse_spec(
+        patch_spec,
+        &stack.state.applied,
+        &stack.state.unapplied,
+        stack.state.applied.last(),
+    ) {
+        Some(result) => result?,
+        None => Destination::Patch(resolve_patchname(&stack, patch_spec.parse()?)?),
+    };
+
+    // Figure out the push/pop plan, and any patch-id-based upstream
+    // matches, while `stack` (and its patch commits) are still available
+    // -- `stack` is consumed by `StackTransaction::make_context` below.
+    let plan = match destination {
+        Destination::Base => Plan::Pop(stack.state.applied.clone()),
+        Destination::Patch(patchname) => {
+            if let Some(pos) = stack.state.applied.iter().position(|pn| pn == &patchname) {
+                Plan::Pop(stack.state.applied[pos + 1..].to_vec())
+            } else {
+                let pos = stack
+                    .state
+                    .unapplied
+                    .iter()
+                    .position(|pn| pn == &patchname)
+                    .expect("already determined patch exists and not hidden or applied");
+                let to_apply: Vec<PatchName> = stack.state.unapplied[0..pos + 1].to_vec();
+
+                let patch_id_merged = if opt_merged {
+                    find_patch_id_merged(&repo, &stack, &to_apply)?
+                } else {
+                    vec![]
+                };
+
+                Plan::Push {
+                    to_apply,
+                    patch_id_merged,
+                }
+            }
+        }
+    };
+
+    let discard_changes = false;
+    let use_index_and_worktree = true;
+
+    let trans_context = StackTransaction::make_context(
+        stack,
+        ConflictMode::Disallow,
+        discard_changes,
+        use_index_and_worktree,
+    );
+
+    let exec_context = trans_context.transact(|trans| match &plan {
+        Plan::Pop(to_pop) => {
+            let to_pop: IndexSet<PatchName> = to_pop.iter().cloned().collect();
+            trans.pop_patches(|pn| to_pop.contains(pn));
+            Ok(())
+        }
+        Plan::Push {
+            to_apply,
+            patch_id_merged,
+        } => {
+            let mut merged = if opt_merged {
+                trans.check_merged(to_apply)?
+            } else {
+                vec![]
+            };
+            for pn in patch_id_merged {
+                if !merged.contains(pn) {
+                    merged.push(pn.clone());
+                }
+            }
+
+            let mut stdout = crate::color::get_color_stdout(matches);
+            let mut _color_spec = termcolor::ColorSpec::new();
+
+            if opt_merged {
+                if merged.len() == 1 {
+                    writeln!(stdout, "Found 1 patch merged upstream")?;
+                } else {
+                    writeln!(stdout, "Found {} patches merged upstream", merged.len())?;
+                }
+            }
+
+            for patchname in to_apply {
+                let already_merged = merged.contains(patchname);
+                trans.push_patch(patchname, already_merged)?;
+            }
+
+            Ok(())
+        }
+    });
+
+    exec_context.execute("goto")?;
+
+    Ok(())
+}
+
+enum Plan {
+    Pop(Vec<PatchName>),
+    Push {
+        to_apply: Vec<PatchName>,
+        patch_id_merged: Vec<PatchName>,
+    },
+}
+
+/// Find patches in `to_apply` whose patch id (see [`crate::patchid`])
+/// matches a commit reachable from the current branch's upstream, i.e.
+/// patches that were merged upstream under a different commit id (after
+/// a rebase or cherry-pick). The result is unioned with the reachability
+/// -based check that `StackTransaction::check_merged` does.
+///
+/// This is a `goto`-local workaround, not the general behavior the
+/// patch-id request asks for: `check_merged` itself still only does the
+/// reachability check, so any other caller of `check_merged` (current or
+/// future) will not see patch-id-based matches. Moving this logic into
+/// `check_merged` belongs in the stack module, which isn't part of this
+/// checkout.
+fn find_patch_id_merged(
+    repo: &git2::Repository,
+    stack: &Stack,
+    to_apply: &[PatchName],
+) -> Result<Vec<PatchName>, Error> {
+    let branch = git2::Branch::wrap(repo.head()?);
+    let upstream = match branch.upstream() {
+        Ok(upstream) => upstream,
+        Err(_) => return Ok(vec![]),
+    };
+
+    let branch_oid = branch
+        .get()
+        .target()
+        .ok_or_else(|| Error::Generic("current branch has no commits".to_string()))?;
+    let upstream_oid = upstream
+        .get()
+        .target()
+        .ok_or_else(|| Error::Generic("upstream branch has no commits".to_string()))?;
+    let merge_base = repo.merge_base(branch_oid, upstream_oid)?;
+
+    let upstream_ids = UpstreamPatchIds::build(repo, merge_base, upstream_oid)?;
+
+    let mut merged = Vec::new();
+    for pn in to_apply {
+        if let Some(desc) = stack.state.patches.get(pn) {
+            if let Some(id) = patchid::patch_id(repo, &desc.commit)? {
+                if upstream_ids.contains(id) {
+                    merged.push(pn.clone());
+                }
+            }
+        }
+    }
+    Ok(merged)
+}
+
+/// Resolve an exact `PatchName`, falling back to a fuzzy jaro-winkler
+/// suggestion or a raw OID prefix when it isn't a patch in the stack.
+fn resolve_patchname(stack: &Stack, patchname: PatchName) -> Result<PatchName, Error> {
+    if stack.state.patches.contains_key(&patchname) {
         if stack.state.hidden.contains(&patchname) {
             Err(Error::Generic("Cannot goto a hidden patch".to_string()))
         } else {
@@ -107,59 +271,5 @@ fn run(matches: &ArgMatches) -> super::Result {
                 &patchname
             )))
         }
-    }?;
-
-    let discard_changes = false;
-    let use_index_and_worktree = true;
-
-    let trans_context = StackTransaction::make_context(
-        stack,
-        ConflictMode::Disallow,
-        discard_changes,
-        use_index_and_worktree,
-    );
-
-    let exec_context = trans_context.transact(|trans| {
-        if let Some(pos) = trans.applied().iter().position(|pn| pn == &patchname) {
-            let to_pop: IndexSet<PatchName> = trans.applied()[pos + 1..].iter().cloned().collect();
-            trans.pop_patches(|pn| to_pop.contains(pn));
-            Ok(())
-        } else {
-            let pos = trans
-                .unapplied()
-                .iter()
-                .position(|pn| pn == &patchname)
-                .expect("already determined patch exists and not hidden or applied");
-
-            let to_apply: Vec<PatchName> = trans.unapplied()[0..pos + 1].to_vec();
-
-            let merged = if opt_merged {
-                trans.check_merged(&to_apply)?
-            } else {
-                vec![]
-            };
-
-            let mut stdout = crate::color::get_color_stdout(matches);
-            let mut _color_spec = termcolor::ColorSpec::new();
-
-            if opt_merged {
-                if merged.len() == 1 {
-                    writeln!(stdout, "Found 1 patch merged upstream")?;
-                } else {
-                    writeln!(stdout, "Found {} patches merged upstream", merged.len())?;
-                }
-            }
-
-            for patchname in &to_apply {
-                let already_merged = merged.contains(&patchname);
-                trans.push_patch(patchname, already_merged)?;
-            }
-
-            Ok(())
-        }
-    });
-
-    exec_context.execute("goto")?;
-
-    Ok(())
+    }
 }
@@ -0,0 +1,203 @@
+//! Support for cascading patch rewrites ("evolution") through a stack.
+//!
+//! When a patch's commit is rewritten (as `new --refresh`, `edit`, or
+//! `refresh` do), any patch stacked on top of the old commit would
+//! otherwise be left referencing a parent that no longer exists in the
+//! stack's history. [`recreate_patch`] re-applies such a patch's changes
+//! onto its parent's rewritten commit by cherry-picking it, and
+//! [`evolve_stack`] drives that cascade across every descendant patch, so
+//! a single rewrite propagates all the way to the top of the stack
+//! instead of leaving the patches above it orphaned.
+//!
+//! Calling `evolve_stack` from `StackTransaction`'s commit machinery
+//! after a patch's commit is rewritten -- so the cascade runs inside the
+//! same transaction and a conflict can be surfaced through
+//! `ConflictMode` -- belongs in the stack module. Unlike `goto --merged`
+//! (`crate::cmd::goto`), there's no command in this checkout that
+//! rewrites an existing patch's commit in the first place (`new` only
+//! ever creates a brand-new patch) -- `refresh` and `edit`, the actual
+//! triggers, aren't part of this checkout -- so `evolve_stack` has no CLI
+//! call site to wire into yet; it's exercised directly by the tests
+//! below instead.
+
+use std::collections::HashMap;
+
+use crate::{error::Error, patchname::PatchName};
+
+/// Re-create `patch_commit` on top of `new_parent` by cherry-picking it
+/// (diffing it against its own parent and replaying that delta onto
+/// `new_parent`), preserving the patch's author, message, and
+/// timestamps. Returns the new commit id.
+///
+/// Fails if the cherry-pick produces conflicts; the caller is expected
+/// to surface this through `ConflictMode` so the user can resolve and
+/// continue the cascade.
+pub(crate) fn recreate_patch(
+    repo: &git2::Repository,
+    patch_commit: &git2::Commit,
+    new_parent: &git2::Commit,
+) -> Result<git2::Oid, Error> {
+    let mainline = 0;
+    let mut index = repo.cherrypick_commit(patch_commit, new_parent, mainline, None)?;
+
+    if index.has_conflicts() {
+        return Err(Error::Generic(format!(
+            "patch `{}` conflicts when evolved onto its new parent",
+            patch_commit.id()
+        )));
+    }
+
+    let tree_oid = index.write_tree_to(repo)?;
+    let tree = repo.find_tree(tree_oid)?;
+
+    let new_commit_id = repo.commit(
+        None,
+        &patch_commit.author(),
+        &patch_commit.committer(),
+        &String::from_utf8_lossy(patch_commit.message_raw_bytes()),
+        &tree,
+        &[new_parent],
+    )?;
+
+    Ok(new_commit_id)
+}
+
+/// Cascade a rewrite through `applied`'s descendants.
+///
+/// `rewrites` maps old commit ids to their replacements, seeded by the
+/// caller with the commit that was just rewritten. `applied` must be
+/// given in stack order (oldest first); each patch whose first parent is
+/// a key in `rewrites` is re-created on top of the replacement via
+/// [`recreate_patch`], and its own old-to-new mapping is added to
+/// `rewrites` so later patches in the same call pick up the cascade.
+/// Patches whose parent was never rewritten are left untouched.
+pub(crate) fn evolve_stack(
+    repo: &git2::Repository,
+    applied: &[(PatchName, git2::Commit)],
+    rewrites: &mut HashMap<git2::Oid, git2::Oid>,
+) -> Result<(), Error> {
+    for (_patchname, commit) in applied {
+        let parent_id = match commit.parent_id(0) {
+            Ok(id) => id,
+            Err(_) => continue,
+        };
+        if let Some(&new_parent_id) = rewrites.get(&parent_id) {
+            let new_parent = repo.find_commit(new_parent_id)?;
+            let new_commit_id = recreate_patch(repo, commit, &new_parent)?;
+            rewrites.insert(commit.id(), new_commit_id);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A throwaway repository under the system temp dir, cleaned up on
+    /// drop, used instead of a `tempfile` dependency since there's no
+    /// `Cargo.toml` in this checkout to register one in.
+    struct TempRepo {
+        path: std::path::PathBuf,
+        repo: git2::Repository,
+    }
+
+    impl TempRepo {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "stgit-evolve-test-{}-{}",
+                name,
+                std::process::id()
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            let repo = git2::Repository::init(&path).expect("init temp repo");
+            Self { path, repo }
+        }
+
+        fn commit(&self, parent: Option<&git2::Commit>, file: &str, content: &str) -> git2::Oid {
+            let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+            let mut builder = self.repo.treebuilder(None).unwrap();
+            if let Some(parent) = parent {
+                let parent_tree = parent.tree().unwrap();
+                for entry in parent_tree.iter().filter(|e| e.name() != Some(file)) {
+                    builder
+                        .insert(entry.name().unwrap(), entry.id(), entry.filemode())
+                        .unwrap();
+                }
+            }
+            let blob = self.repo.blob(content.as_bytes()).unwrap();
+            builder.insert(file, blob, 0o100644).unwrap();
+            let tree_id = builder.write().unwrap();
+            let tree = self.repo.find_tree(tree_id).unwrap();
+            let parents: Vec<&git2::Commit> = parent.into_iter().collect();
+            self.repo
+                .commit(None, &sig, &sig, "test commit", &tree, &parents)
+                .unwrap()
+        }
+    }
+
+    impl Drop for TempRepo {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn cascades_a_rewrite_through_descendant_patches() {
+        let t = TempRepo::new("cascade");
+
+        let root_id = t.commit(None, "base.txt", "base\n");
+        let root = t.repo.find_commit(root_id).unwrap();
+
+        let a_id = t.commit(Some(&root), "a.txt", "a\n");
+        let a = t.repo.find_commit(a_id).unwrap();
+        let b_id = t.commit(Some(&a), "b.txt", "b\n");
+        let b = t.repo.find_commit(b_id).unwrap();
+        let c_id = t.commit(Some(&b), "c.txt", "c\n");
+        let c = t.repo.find_commit(c_id).unwrap();
+
+        // Simulate 'a' having just been rewritten (e.g. by a refresh)
+        // onto a new commit with different content, and so a different id.
+        let a_new_id = t.commit(Some(&root), "a.txt", "a, refreshed\n");
+
+        let mut rewrites = HashMap::new();
+        rewrites.insert(a_id, a_new_id);
+
+        let applied = vec![
+            ("b".parse::<PatchName>().unwrap(), b),
+            ("c".parse::<PatchName>().unwrap(), c),
+        ];
+        evolve_stack(&t.repo, &applied, &mut rewrites).unwrap();
+
+        let new_b_id = *rewrites.get(&b_id).expect("b was cascaded");
+        let new_b = t.repo.find_commit(new_b_id).unwrap();
+        assert_eq!(new_b.parent_id(0).unwrap(), a_new_id);
+
+        let new_c_id = *rewrites.get(&c_id).expect("c was cascaded");
+        let new_c = t.repo.find_commit(new_c_id).unwrap();
+        assert_eq!(new_c.parent_id(0).unwrap(), new_b_id);
+
+        // Content each patch introduced is preserved through the cascade.
+        let new_b_tree = new_b.tree().unwrap();
+        assert!(new_b_tree.get_name("b.txt").is_some());
+        assert!(new_b_tree.get_name("a.txt").is_some());
+        let new_c_tree = new_c.tree().unwrap();
+        assert!(new_c_tree.get_name("c.txt").is_some());
+    }
+
+    #[test]
+    fn patches_whose_parent_was_not_rewritten_are_left_alone() {
+        let t = TempRepo::new("untouched");
+
+        let root_id = t.commit(None, "base.txt", "base\n");
+        let root = t.repo.find_commit(root_id).unwrap();
+        let a_id = t.commit(Some(&root), "a.txt", "a\n");
+        let a = t.repo.find_commit(a_id).unwrap();
+
+        let mut rewrites = HashMap::new();
+        let applied = vec![("a".parse::<PatchName>().unwrap(), a)];
+        evolve_stack(&t.repo, &applied, &mut rewrites).unwrap();
+
+        assert!(rewrites.is_empty());
+    }
+}
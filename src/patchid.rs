@@ -0,0 +1,212 @@
+//! Patch-id computation for detecting upstream-equivalent commits.
+//!
+//! Mirrors `git patch-id`: a commit's diff against its first parent is
+//! normalized by dropping the `diff --git`/`index`/`---`/`+++` file
+//! header lines entirely (they embed the whole file's before/after blob
+//! hashes, which shift with unrelated changes elsewhere in the file),
+//! stripping hunk-header line numbers, and trimming surrounding
+//! whitespace from the remaining content lines, then hashing the result.
+//! Two commits with equal patch ids represent the same logical change
+//! even if they were rebased or cherry-picked onto a different parent,
+//! which lets [`goto`](crate::cmd::goto) recognize `--merged` patches
+//! that were applied upstream under a different commit id.
+
+use std::collections::HashSet;
+
+use crate::error::Error;
+
+/// A normalized, hashed representation of a commit's diff.
+pub(crate) type PatchId = git2::Oid;
+
+/// Compute the patch id of `commit` relative to its first parent.
+///
+/// Returns `Ok(None)` for an empty or all-binary diff, which never
+/// matches any other patch id.
+pub(crate) fn patch_id(repo: &git2::Repository, commit: &git2::Commit) -> Result<Option<PatchId>, Error> {
+    let tree = commit.tree()?;
+    let parent_tree = commit.parents().next().map(|p| p.tree()).transpose()?;
+    let diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+
+    let mut normalized = String::new();
+    diff.print(git2::DiffFormat::Patch, |delta, _hunk, line| {
+        if delta.old_file().is_binary() || delta.new_file().is_binary() {
+            return true;
+        }
+        match line.origin() {
+            // File header: `diff --git`, `index`, `---`, `+++`. Dropped
+            // entirely -- the `index` line's blob hashes depend on the
+            // whole file, not just the hunk this patch touches.
+            'F' => {}
+            // Hunk header: `@@ -a,b +c,d @@ context`. Keep only the
+            // trailing context text.
+            'H' => {
+                normalized.push_str(&strip_hunk_header(&String::from_utf8_lossy(line.content())));
+                normalized.push('\n');
+            }
+            _ => {
+                normalized.push(line.origin());
+                normalized.push_str(String::from_utf8_lossy(line.content()).trim());
+                normalized.push('\n');
+            }
+        }
+        true
+    })?;
+
+    if normalized.trim().is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(git2::Oid::hash_object(
+            git2::ObjectType::Blob,
+            normalized.as_bytes(),
+        )?))
+    }
+}
+
+/// Strip the `-a,b +c,d` line-number portion of a hunk header, leaving
+/// only the trailing function-context text.
+fn strip_hunk_header(line: &str) -> String {
+    if let Some(rest) = line.strip_prefix("@@") {
+        if let Some(end) = rest.find("@@") {
+            return rest[end + 2..].trim().to_string();
+        }
+    }
+    line.trim().to_string()
+}
+
+/// The set of patch ids of every commit between a merge-base and an
+/// upstream ref, computed once per invocation and reused across all
+/// candidate patches to avoid O(n*m) diffing.
+pub(crate) struct UpstreamPatchIds {
+    ids: HashSet<PatchId>,
+}
+
+impl UpstreamPatchIds {
+    pub(crate) fn build(
+        repo: &git2::Repository,
+        merge_base: git2::Oid,
+        upstream: git2::Oid,
+    ) -> Result<Self, Error> {
+        let mut walk = repo.revwalk()?;
+        walk.push(upstream)?;
+        walk.hide(merge_base)?;
+
+        let mut ids = HashSet::new();
+        for oid in walk {
+            let commit = repo.find_commit(oid?)?;
+            if let Some(id) = patch_id(repo, &commit)? {
+                ids.insert(id);
+            }
+        }
+        Ok(Self { ids })
+    }
+
+    pub(crate) fn contains(&self, id: PatchId) -> bool {
+        self.ids.contains(&id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_hunk_header_numbers_but_keeps_context() {
+        assert_eq!(
+            strip_hunk_header("@@ -12,7 +34,9 @@ fn foo() {"),
+            "fn foo() {"
+        );
+        assert_eq!(strip_hunk_header("@@ -1 +1 @@"), "");
+    }
+
+    #[test]
+    fn strip_hunk_header_only_trims_a_non_header_line() {
+        assert_eq!(strip_hunk_header("  some content  "), "some content");
+    }
+
+    /// A throwaway repository under the system temp dir, cleaned up on
+    /// drop. Built with plain git2 calls so these tests don't depend on
+    /// the `stack`/`repo` plumbing this checkout doesn't have.
+    struct TempRepo {
+        path: std::path::PathBuf,
+        repo: git2::Repository,
+    }
+
+    impl TempRepo {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "stgit-patchid-test-{}-{}-{}",
+                name,
+                std::process::id(),
+                name.len()
+            ));
+            let _ = std::fs::remove_dir_all(&path);
+            let repo = git2::Repository::init(&path).expect("init temp repo");
+            Self { path, repo }
+        }
+
+        fn commit(&self, parent: Option<&git2::Commit>, file: &str, content: &str) -> git2::Oid {
+            let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+            let mut builder = self.repo.treebuilder(None).unwrap();
+            if let Some(parent) = parent {
+                let parent_tree = parent.tree().unwrap();
+                if let Some(entry) = parent_tree.iter().find(|e| e.name() != Some(file)) {
+                    builder.insert(entry.name().unwrap(), entry.id(), entry.filemode()).unwrap();
+                }
+            }
+            let blob = self.repo.blob(content.as_bytes()).unwrap();
+            builder.insert(file, blob, 0o100644).unwrap();
+            let tree_id = builder.write().unwrap();
+            let tree = self.repo.find_tree(tree_id).unwrap();
+            let parents: Vec<&git2::Commit> = parent.into_iter().collect();
+            self.repo
+                .commit(None, &sig, &sig, "test commit", &tree, &parents)
+                .unwrap()
+        }
+    }
+
+    impl Drop for TempRepo {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.path);
+        }
+    }
+
+    #[test]
+    fn same_edit_on_different_parents_has_equal_patch_id() {
+        let t = TempRepo::new("equal");
+
+        let base_id = t.commit(None, "unrelated.txt", "unrelated");
+        let base = t.repo.find_commit(base_id).unwrap();
+
+        let other_base_id = t.commit(None, "unrelated.txt", "a different unrelated state");
+        let other_base = t.repo.find_commit(other_base_id).unwrap();
+
+        let a_id = t.commit(Some(&base), "a.txt", "hello\n");
+        let a = t.repo.find_commit(a_id).unwrap();
+
+        let b_id = t.commit(Some(&other_base), "a.txt", "hello\n");
+        let b = t.repo.find_commit(b_id).unwrap();
+
+        let id_a = patch_id(&t.repo, &a).unwrap();
+        let id_b = patch_id(&t.repo, &b).unwrap();
+        assert!(id_a.is_some());
+        assert_eq!(id_a, id_b);
+    }
+
+    #[test]
+    fn different_edits_have_different_patch_ids() {
+        let t = TempRepo::new("different");
+
+        let base_id = t.commit(None, "unrelated.txt", "unrelated");
+        let base = t.repo.find_commit(base_id).unwrap();
+
+        let a_id = t.commit(Some(&base), "a.txt", "hello\n");
+        let a = t.repo.find_commit(a_id).unwrap();
+
+        let b_id = t.commit(Some(&base), "a.txt", "goodbye\n");
+        let b = t.repo.find_commit(b_id).unwrap();
+
+        let id_a = patch_id(&t.repo, &a).unwrap();
+        let id_b = patch_id(&t.repo, &b).unwrap();
+        assert_ne!(id_a, id_b);
+    }
+}
@@ -0,0 +1,264 @@
+//! Parsing for relative and topological patch specifications.
+//!
+//! Extends plain [`PatchName`] lookup with git `rev-parse`-style
+//! navigation: `{base}` for the stack base, a signed offset such as `+2`
+//! or `-3` relative to the current top, and a `~N` (or `^` for `~1`)
+//! suffix on a patch name meaning "N patches earlier in stack order".
+//! Commands try [`parse_spec`] on a user-supplied patch argument first;
+//! it returns `None` for a plain patch name so the caller can fall back
+//! to its own exact-name, fuzzy, or OID lookup.
+
+use crate::{error::Error, patchname::PatchName};
+
+/// Where a resolved patch spec points to.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) enum Destination {
+    /// A specific patch, already applied or pushable from unapplied.
+    Patch(PatchName),
+    /// `{base}`: below every applied patch, i.e. pop the whole stack.
+    Base,
+}
+
+/// Resolve `spec` against the stack's applied and unapplied patches,
+/// given in stack order (oldest first), and the currently applied top
+/// patch, if any.
+///
+/// Returns `None` if `spec` doesn't use any of the relative/topological
+/// grammar, meaning the caller should treat it as a plain patch name.
+pub(crate) fn parse_spec(
+    spec: &str,
+    applied: &[PatchName],
+    unapplied: &[PatchName],
+    top: Option<&PatchName>,
+) -> Option<Result<Destination, Error>> {
+    if spec == "{base}" {
+        return Some(if applied.is_empty() && unapplied.is_empty() {
+            Err(Error::Generic("stack is empty".to_string()))
+        } else {
+            Ok(Destination::Base)
+        });
+    }
+
+    if let Some(offset) = parse_signed_offset(spec) {
+        return Some(resolve_offset(offset, applied, unapplied, top).map(Destination::Patch));
+    }
+
+    if let Some((name, n)) = split_tilde_suffix(spec) {
+        return Some(
+            name.parse::<PatchName>()
+                .and_then(|name| resolve_tilde(&name, n, applied, unapplied))
+                .map(Destination::Patch),
+        );
+    }
+
+    None
+}
+
+/// Parse a leading `+`/`-` sign followed by digits, e.g. `+2`, `-3`. A
+/// bare `+` or `-` is an offset of 1.
+fn parse_signed_offset(spec: &str) -> Option<i64> {
+    let mut chars = spec.chars();
+    let sign = match chars.next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let digits = chars.as_str();
+    if digits.is_empty() {
+        return Some(sign);
+    }
+    digits.parse::<i64>().ok().map(|n| sign * n)
+}
+
+/// Resolve a signed offset relative to the current top, against the
+/// combined applied-then-unapplied sequence: negative offsets move
+/// further down the applied patches, positive offsets move up into the
+/// unapplied patches (so `+1` is "push the next unapplied patch").
+fn resolve_offset(
+    offset: i64,
+    applied: &[PatchName],
+    unapplied: &[PatchName],
+    top: Option<&PatchName>,
+) -> Result<PatchName, Error> {
+    let top_pos = match top {
+        Some(top) => applied
+            .iter()
+            .position(|pn| pn == top)
+            .expect("current top is applied"),
+        None => {
+            return Err(Error::Generic(
+                "no patches are applied; cannot use a relative offset".to_string(),
+            ))
+        }
+    };
+
+    let target = top_pos as i64 + offset;
+    if target < 0 {
+        return Err(Error::Generic(format!(
+            "offset `{}` is out of range of the stack",
+            fmt_offset(offset)
+        )));
+    }
+    let target = target as usize;
+
+    if let Some(pn) = applied.get(target) {
+        Ok(pn.clone())
+    } else {
+        unapplied
+            .get(target - applied.len())
+            .cloned()
+            .ok_or_else(|| {
+                Error::Generic(format!(
+                    "offset `{}` is out of range of the stack",
+                    fmt_offset(offset)
+                ))
+            })
+    }
+}
+
+fn fmt_offset(offset: i64) -> String {
+    if offset >= 0 {
+        format!("+{}", offset)
+    } else {
+        offset.to_string()
+    }
+}
+
+/// Split a trailing `~N` or `^` suffix off a patch name, returning the
+/// bare name and the number of patches to step back. `^` is `~1`.
+fn split_tilde_suffix(spec: &str) -> Option<(&str, usize)> {
+    if let Some(name) = spec.strip_suffix('^') {
+        return Some((name, 1));
+    }
+    let (name, n) = spec.rsplit_once('~')?;
+    let n: usize = n.parse().ok()?;
+    Some((name, n))
+}
+
+fn resolve_tilde(
+    name: &PatchName,
+    n: usize,
+    applied: &[PatchName],
+    unapplied: &[PatchName],
+) -> Result<PatchName, Error> {
+    let sequence: Vec<&PatchName> = applied.iter().chain(unapplied.iter()).collect();
+    let pos = sequence
+        .iter()
+        .position(|pn| *pn == name)
+        .ok_or_else(|| Error::Generic(format!("patch `{}` does not exist", name)))?;
+
+    pos.checked_sub(n)
+        .map(|i| sequence[i].clone())
+        .ok_or_else(|| Error::Generic(format!("`{}~{}` underruns the stack", name, n)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pn(s: &str) -> PatchName {
+        s.parse().unwrap()
+    }
+
+    fn expect_patch(result: Option<Result<Destination, Error>>) -> PatchName {
+        match result {
+            Some(Ok(Destination::Patch(name))) => name,
+            other => panic!("expected a resolved patch, got {}", describe(&other)),
+        }
+    }
+
+    fn expect_base(result: Option<Result<Destination, Error>>) {
+        match result {
+            Some(Ok(Destination::Base)) => {}
+            other => panic!("expected Destination::Base, got {}", describe(&other)),
+        }
+    }
+
+    fn expect_err(result: Option<Result<Destination, Error>>) {
+        match result {
+            Some(Err(_)) => {}
+            other => panic!("expected an error, got {}", describe(&other)),
+        }
+    }
+
+    fn describe(result: &Option<Result<Destination, Error>>) -> &'static str {
+        match result {
+            Some(Ok(Destination::Patch(_))) => "Some(Ok(Patch))",
+            Some(Ok(Destination::Base)) => "Some(Ok(Base))",
+            Some(Err(_)) => "Some(Err)",
+            None => "None",
+        }
+    }
+
+    #[test]
+    fn base_pops_the_whole_stack() {
+        let applied = vec![pn("a"), pn("b")];
+        let unapplied = vec![pn("c")];
+        expect_base(parse_spec("{base}", &applied, &unapplied, applied.last()));
+    }
+
+    #[test]
+    fn base_on_empty_stack_errors() {
+        expect_err(parse_spec("{base}", &[], &[], None));
+    }
+
+    #[test]
+    fn positive_offset_reaches_into_unapplied() {
+        let applied = vec![pn("a"), pn("b")];
+        let unapplied = vec![pn("c"), pn("d")];
+        let top = applied.last();
+
+        assert_eq!(
+            expect_patch(parse_spec("+1", &applied, &unapplied, top)).as_ref(),
+            "c"
+        );
+        assert_eq!(
+            expect_patch(parse_spec("+2", &applied, &unapplied, top)).as_ref(),
+            "d"
+        );
+    }
+
+    #[test]
+    fn negative_offset_reaches_into_applied() {
+        let applied = vec![pn("a"), pn("b"), pn("c")];
+        let top = applied.last();
+
+        assert_eq!(
+            expect_patch(parse_spec("-1", &applied, &[], top)).as_ref(),
+            "b"
+        );
+        assert_eq!(
+            expect_patch(parse_spec("-2", &applied, &[], top)).as_ref(),
+            "a"
+        );
+    }
+
+    #[test]
+    fn offset_out_of_range_errors() {
+        let applied = vec![pn("a")];
+        expect_err(parse_spec("+1", &applied, &[], applied.last()));
+        expect_err(parse_spec("-5", &applied, &[], applied.last()));
+    }
+
+    #[test]
+    fn tilde_and_caret_step_back_through_stack_order() {
+        let applied = vec![pn("a"), pn("b")];
+        let unapplied = vec![pn("c")];
+        let top = applied.last();
+
+        assert_eq!(
+            expect_patch(parse_spec("c~2", &applied, &unapplied, top)).as_ref(),
+            "a"
+        );
+        assert_eq!(
+            expect_patch(parse_spec("b^", &applied, &unapplied, top)).as_ref(),
+            "a"
+        );
+    }
+
+    #[test]
+    fn plain_name_is_not_a_spec() {
+        let applied = vec![pn("a")];
+        assert!(parse_spec("a", &applied, &[], applied.last()).is_none());
+    }
+}